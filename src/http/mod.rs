@@ -1,4 +1,4 @@
-use std::io::IoResult;
+use std::io::{IoError, IoResult};
 use std::str::from_utf8;
 use std::collections::HashMap;
 
@@ -8,6 +8,8 @@ use std::fmt::Display;
 use std::fmt::Formatter;
 
 pub mod parser;
+pub mod server;
+pub mod writer;
 
 #[derive(Debug)]
 pub enum HTTPError {
@@ -17,7 +19,15 @@ pub enum HTTPError {
     MalformedHeaderLineError,
     BodyParsingError,
     StatusCodeParseError,
-    StatusReasonParseError
+    StatusReasonParseError,
+    // A read or write against the underlying socket failed outright (reset,
+    // timed out, etc). Carries the original `IoError` for whoever logs it.
+    IoError(IoError),
+    // The peer closed the connection mid-message: a `read` returned zero
+    // bytes while a parser still expected more. Distinct from `IoError`
+    // because this is the ordinary shape of a client hanging up early,
+    // not a socket failure.
+    UnexpectedEof
 }
 
 impl Error for HTTPError {
@@ -29,7 +39,9 @@ impl Error for HTTPError {
            HTTPError::MalformedHeaderLineError => "MalformedHeaderLineError",
            HTTPError::BodyParsingError => "BodyParsingError",
            HTTPError::StatusCodeParseError => "StatusCodeParseError",
-           HTTPError::StatusReasonParseError => "StatusReasonParseError"
+           HTTPError::StatusReasonParseError => "StatusReasonParseError",
+           HTTPError::IoError(_) => "IoError",
+           HTTPError::UnexpectedEof => "UnexpectedEof"
         }
     }
 
@@ -93,6 +105,47 @@ pub struct Request {
     pub body: Option<String>
 }
 
+impl Request {
+    // Whether the connection this request arrived on should stay open for
+    // another request, per the HTTP/1.0 and HTTP/1.1 `Connection` rules:
+    // HTTP/1.0 defaults to close and opts in via `keep-alive`, HTTP/1.1
+    // defaults to keep-alive and opts out via `close`. An `upgrade` value
+    // means the connection is being handed off to another protocol, so it
+    // isn't available for another HTTP request either way.
+    pub fn keep_alive(&self) -> bool {
+        let connection = self.headers.get("Connection");
+
+        if let Some(v) = connection {
+            if parser::header_val_contains(v, "upgrade") {
+                return false;
+            }
+        }
+
+        return match self.version {
+            Version::Http10 => match connection {
+                Some(v) => parser::header_val_contains(v, "keep-alive"),
+                None => false
+            },
+            Version::Http11 => match connection {
+                Some(v) => !parser::header_val_contains(v, "close"),
+                None => true
+            },
+            _ => false
+        };
+    }
+}
+
+// What `parser::read_request` found at the start of the connection: either
+// a fully parsed HTTP/1.x request, or the HTTP/2 client connection preface,
+// which isn't an HTTP/1.x request at all and needs to be handed off to an
+// h2-aware path instead of being parsed (and rejected) as malformed HTTP/1.
+// h2 itself isn't implemented here; this is only the detection signal.
+#[derive(Debug)]
+pub enum ParsedItem {
+    Request(Request),
+    Http2Preface
+}
+
 #[derive(Debug)]
 pub struct Response {
     pub version: Version,
@@ -2,9 +2,16 @@ use std::io::{TcpListener, TcpStream};
 use std::io::{Acceptor, Listener};
 use std::io::IoResult;
 use std::str::from_utf8;
+use std::num::from_str_radix;
 use std::collections::HashMap;
 
-use http::{RequestType, HeaderVal, Version, Error, Request, Response};
+use http::{RequestType, HeaderVal, Version, HTTPError, ParsedItem, Request, Response};
+
+// The start of the HTTP/2 client connection preface (RFC 7540 section 3.1):
+// `PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n`. An HTTP/1.x request line can never begin
+// this way, so matching just these 14 bytes is enough to tell an h2 client
+// apart from a malformed HTTP/1.x one.
+const HTTP2_PREFACE_PREFIX: &'static [u8] = b"PRI * HTTP/2.0";
 
 // Tokens
 const CR: u8 = b'\r';
@@ -14,118 +21,222 @@ const COLON: u8 = b':';
 const COMMA: u8 = b',';
 const DQUOTE: u8 = b'"';
 
+const READ_CHUNK_LEN: usize = 8192us;
+
+// What a single call to `Parser::parse` can report: either it found its
+// delimiter somewhere in the buffered bytes (and consumed up to and
+// including it), or the buffer ran out before the delimiter showed up and
+// the `Reader` needs to pull more bytes off the socket before trying again.
+enum ParseStatus<T> {
+    Done(T, usize),
+    NeedMore
+}
+
+// A parser no longer owns the socket: it is handed whatever has already
+// been buffered and either finishes (returning how many bytes of `buf` it
+// consumed) or asks for more. This lets `Reader` fill the buffer in large
+// `read()` calls instead of the old one-byte-at-a-time `read_byte` loop.
 trait Parser {
-    fn read_req_component(&mut self, stream: &mut TcpStream) -> Vec<u8>;
+    type Output;
+
+    fn parse(&mut self, buf: &[u8]) -> Result<ParseStatus<Self::Output>, HTTPError>;
 }
 
-struct SPParser {
+// Owns the growable read buffer for a connection and the cursor into it.
+// Component parsers are handed `&buf[pos..filled]` and told how many bytes
+// they consumed; bytes left over after a parse (e.g. the start of the next
+// pipelined request, or of the body right after the headers) stay in the
+// buffer for the next call instead of being read twice.
+pub struct Reader<'a> {
+    stream: &'a mut TcpStream,
     buf: Vec<u8>,
-    max_token_len: usize
+    pos: usize,
+    filled: usize
 }
 
-impl SPParser {
-    fn new() -> SPParser {
-        SPParser { buf: Vec::new(), max_token_len: 4096us }
+impl<'a> Reader<'a> {
+    pub fn new(stream: &'a mut TcpStream) -> Reader<'a> {
+        Reader { stream: stream, buf: Vec::with_capacity(READ_CHUNK_LEN), pos: 0, filled: 0 }
     }
-}
 
-impl Parser for SPParser {
-    fn read_req_component(&mut self, stream: &mut TcpStream) -> Vec<u8> {
-        // Reset parser state
+    // True once every buffered byte has been consumed by a parser, i.e.
+    // there is no pipelined data waiting to be parsed as a next request.
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.filled
+    }
+
+    fn compact(&mut self) {
+        if self.pos == 0 { return; }
+        let remaining = self.buf[self.pos..self.filled].to_vec();
         self.buf.clear();
+        self.buf.push_all(remaining.as_slice());
+        self.filled = self.buf.len();
+        self.pos = 0;
+    }
 
+    // A zero-byte read means the peer closed the connection; any other
+    // error is surfaced as-is so the caller can decide what to do with a
+    // broken socket rather than this code crashing the serving task.
+    fn fill(&mut self) -> Result<usize, HTTPError> {
+        self.compact();
+
+        let mut chunk = [0u8; READ_CHUNK_LEN];
+        let n = match self.stream.read(&mut chunk) {
+            Ok(n) => n,
+            Err(e) => return Err(HTTPError::IoError(e))
+        };
+        if n == 0 { return Err(HTTPError::UnexpectedEof); }
+
+        self.buf.push_all(chunk[0..n].as_slice());
+        self.filled = self.buf.len();
+        return Ok(n);
+    }
+
+    // Reads a single already-buffered byte, topping up the buffer first if
+    // it has run dry. Used by the chunked-body state machine, which needs
+    // to walk the stream byte-by-byte but must still draw from whatever the
+    // `Reader` already pulled off the socket rather than reading around it.
+    fn next_byte(&mut self) -> Result<u8, HTTPError> {
+        while self.pos >= self.filled {
+            try!(self.fill());
+        }
+        let byte = self.buf[self.pos];
+        self.pos += 1;
+        return Ok(byte);
+    }
+
+    // Looks at the next `n` buffered bytes without consuming them, filling
+    // the buffer as needed. Used to sniff the HTTP/2 preface before
+    // committing to parsing an HTTP/1.x request line.
+    fn peek(&mut self, n: usize) -> Result<Vec<u8>, HTTPError> {
+        while self.filled - self.pos < n {
+            try!(self.fill());
+        }
+        return Ok(self.buf[self.pos..self.pos + n].to_vec());
+    }
+
+    // Lets a handler write a response back out over the same socket the
+    // `Reader` is buffering reads from, without taking a second `&mut`
+    // borrow of the underlying `TcpStream`.
+    pub fn write_all(&mut self, buf: &[u8]) -> IoResult<()> {
+        self.stream.write(buf)
+    }
+
+    // Hands out the underlying stream so a caller can build a `writer::Writer`
+    // on it to serialize a response, without taking a second borrow alongside
+    // the `Reader` itself.
+    pub fn stream(&mut self) -> &mut TcpStream {
+        self.stream
+    }
+
+    fn parse<P: Parser>(&mut self, parser: &mut P) -> Result<P::Output, HTTPError> {
         loop {
-            let byte = stream.read_byte().unwrap();
-            if self.buf.len() >= self.max_token_len { break; }
-            match byte {
-                SP =>{ break; }
-                _ => { self.buf.push(byte); }
+            let status = try!(parser.parse(self.buf[self.pos..self.filled].as_slice()));
+            match status {
+                ParseStatus::Done(value, consumed) => {
+                    self.pos += consumed;
+                    return Ok(value);
+                }
+                ParseStatus::NeedMore => {
+                    try!(self.fill());
+                }
             }
         }
+    }
+}
+
+struct SPParser {
+    max_token_len: usize
+}
 
-        return self.buf.clone();
+impl SPParser {
+    fn new() -> SPParser {
+        SPParser { max_token_len: 4096us }
     }
 }
 
-#[derive(Show,PartialEq)]
-enum EOLParserState {
-    Token,
-    CR,
-    LF
+impl Parser for SPParser {
+    type Output = Vec<u8>;
+
+    fn parse(&mut self, buf: &[u8]) -> Result<ParseStatus<Vec<u8>>, HTTPError> {
+        for i in range(0, buf.len()) {
+            if buf[i] == SP {
+                return Ok(ParseStatus::Done(buf[0..i].to_vec(), i + 1));
+            }
+        }
+        if buf.len() >= self.max_token_len {
+            return Err(HTTPError::MalformedHeaderLineError);
+        }
+        return Ok(ParseStatus::NeedMore);
+    }
 }
 
 struct EOLParser {
-    buf: Vec<u8>,
-    max_token_len: usize,
-    state: EOLParserState
+    max_token_len: usize
 }
 
 impl EOLParser {
     fn new() -> EOLParser {
-        EOLParser { buf: Vec::new(), max_token_len: 4096us, state: EOLParserState::Token }
+        EOLParser { max_token_len: 4096us }
     }
 }
 
 impl Parser for EOLParser {
-    fn read_req_component(&mut self, stream: &mut TcpStream) -> Vec<u8> {
-        // Reset parser state
-        self.buf.clear();
-
-        loop {
-            let byte = stream.read_byte().unwrap();
-            if self.buf.len() >= self.max_token_len { break; }
-
-            match byte {
-                CR => {
-                    if self.state != EOLParserState::Token { panic!("Parse error!"); }
-                    self.state = EOLParserState::CR;
-                }
-                LF => {
-                    if self.state != EOLParserState::CR { panic!("Parse error!"); }
-                    break;
-                }
-                _ => {
-                    self.buf.push(byte);
-                }
+    type Output = Vec<u8>;
+
+    fn parse(&mut self, buf: &[u8]) -> Result<ParseStatus<Vec<u8>>, HTTPError> {
+        let mut i = 0us;
+        while i < buf.len() {
+            if buf[i] == CR {
+                if i + 1 >= buf.len() { break; }
+                if buf[i + 1] != LF { return Err(HTTPError::MalformedHeaderLineError); }
+                return Ok(ParseStatus::Done(buf[0..i].to_vec(), i + 2));
             }
+            if buf[i] == LF {
+                return Err(HTTPError::MalformedHeaderLineError);
+            }
+            i += 1;
         }
-
-        return self.buf.clone();
+        if buf.len() >= self.max_token_len {
+            return Err(HTTPError::MalformedHeaderLineError);
+        }
+        return Ok(ParseStatus::NeedMore);
     }
 }
 
 struct HeaderKeyParser {
-    buf: Vec<u8>,
     max_token_len: usize
 }
 
 impl HeaderKeyParser {
     fn new() -> HeaderKeyParser {
-        HeaderKeyParser { buf: Vec::new(), max_token_len: 4096us }
+        HeaderKeyParser { max_token_len: 4096us }
     }
 }
 
 impl Parser for HeaderKeyParser {
-    fn read_req_component(&mut self, stream: &mut TcpStream) -> Vec<u8> {
-        // Reset parser state
-        self.buf.clear();
-
-        loop {
-            let byte = stream.read_byte().unwrap();
-            if self.buf.len() >= self.max_token_len { break; }
-            match byte {
-                COLON => { break; }
+    // `Vec::new()` (an empty key) signals the blank line that ends the
+    // header section, same as the empty-string sentinel `read_headers`
+    // used to look for.
+    type Output = Vec<u8>;
+
+    fn parse(&mut self, buf: &[u8]) -> Result<ParseStatus<Vec<u8>>, HTTPError> {
+        let mut i = 0us;
+        while i < buf.len() {
+            match buf[i] {
+                COLON => { return Ok(ParseStatus::Done(buf[0..i].to_vec(), i + 1)); }
                 CR => {
-                    match stream.read_byte().unwrap() {
-                        LF => { break; }
-                        _ => { panic!("Parse error!"); }
-                    }
+                    if i + 1 >= buf.len() { break; }
+                    if buf[i + 1] != LF { return Err(HTTPError::MalformedHeaderLineError); }
+                    return Ok(ParseStatus::Done(Vec::new(), i + 2));
                 }
-                _ => { self.buf.push(byte); }
+                _ => { i += 1; }
             }
         }
-
-        return self.buf.clone();
+        if buf.len() >= self.max_token_len {
+            return Err(HTTPError::MalformedHeaderLineError);
+        }
+        return Ok(ParseStatus::NeedMore);
     }
 }
 
@@ -134,124 +245,274 @@ enum HeaderValParserState {
     Token,
     TokenDelimeter,
     QuotedString,
-    OptionalWhitespace,
-    CR,
-    LF
+    OptionalWhitespace
 }
 
-#[derive(Clone)]
 struct HeaderValParser {
-    buf: Vec<u8>,
-    max_token_len: usize,
-    header_val: HeaderVal,
-    state: HeaderValParserState
+    max_token_len: usize
 }
 
 impl HeaderValParser {
     fn new() -> HeaderValParser {
-        HeaderValParser { buf: Vec::new(), max_token_len: 4096us, state: HeaderValParserState::OptionalWhitespace, header_val: HeaderVal::None }
+        HeaderValParser { max_token_len: 4096us }
     }
+}
 
-    fn read_req_component(&mut self, stream: &mut TcpStream) -> HeaderVal {
-        // Reset parser state
-        self.buf.clear();
-        self.header_val = HeaderVal::None;
-        self.state = HeaderValParserState::OptionalWhitespace;
+impl Parser for HeaderValParser {
+    type Output = HeaderVal;
 
+    fn parse(&mut self, buf: &[u8]) -> Result<ParseStatus<HeaderVal>, HTTPError> {
+        // Find the line terminator first; the header value grammar never
+        // spans multiple lines, so it's simplest to slice out the line and
+        // then run the token/quote/comma state machine over it in one go.
+        let mut eol = 0us;
         loop {
-            let byte = stream.read_byte().unwrap();
-            if self.buf.len() >= self.max_token_len { panic!("Parse error!"); }
-
-            match byte {
-                CR => {
-                    if self.state != HeaderValParserState::Token { panic!("Parse error!") }
-                    self.state = HeaderValParserState::CR;
+            if eol >= buf.len() {
+                if buf.len() >= self.max_token_len {
+                    return Err(HTTPError::MalformedHeaderLineError);
                 }
-                LF => {
-                    if self.state != HeaderValParserState::CR { panic!("Parse error!"); }
-                    break;
+                return Ok(ParseStatus::NeedMore);
+            }
+            if buf[eol] == CR {
+                if eol + 1 >= buf.len() {
+                    if buf.len() >= self.max_token_len {
+                        return Err(HTTPError::MalformedHeaderLineError);
+                    }
+                    return Ok(ParseStatus::NeedMore);
                 }
+                if buf[eol + 1] != LF { return Err(HTTPError::MalformedHeaderLineError); }
+                break;
+            }
+            if buf[eol] == LF {
+                return Err(HTTPError::MalformedHeaderLineError);
+            }
+            eol += 1;
+        }
+
+        let line = buf[0..eol].as_slice();
+        let mut token_buf: Vec<u8> = Vec::new();
+        let mut header_val = HeaderVal::None;
+        let mut state = HeaderValParserState::OptionalWhitespace;
+
+        for i in range(0, line.len()) {
+            let byte = line[i];
+            match byte {
                 SP => {
-                    match self.state {
+                    match state {
                         HeaderValParserState::OptionalWhitespace => { continue; }
-                        HeaderValParserState::Token => { self.state = HeaderValParserState::TokenDelimeter; }
+                        HeaderValParserState::Token => { state = HeaderValParserState::TokenDelimeter; }
                         HeaderValParserState::TokenDelimeter => { continue; }
-                        HeaderValParserState::CR => { panic!("Parse error!"); }
-                        _ => { self.buf.push(byte); }
+                        _ => { token_buf.push(byte); }
                     }
                 }
                 COMMA => {
                     // TODO: consider other "standard" delimeters
-                    self.state = HeaderValParserState::TokenDelimeter;
-                    let str = String::from_utf8(self.buf.clone()).unwrap_or(String::new()).as_slice().trim().into_string();
-                    self.buf.clear();
-                    let new_val = match &self.header_val {
-                        &HeaderVal::None => HeaderVal::Val(str),
-                        &HeaderVal::Val(ref v) => HeaderVal::List(vec!(v.clone(), str)),
-                        &HeaderVal::List(ref list) => { let mut new_list = list.clone(); new_list.push(str); HeaderVal::List(new_list) }
+                    state = HeaderValParserState::TokenDelimeter;
+                    let str = String::from_utf8(token_buf.clone()).unwrap_or(String::new()).as_slice().trim().into_string();
+                    token_buf.clear();
+                    header_val = match header_val {
+                        HeaderVal::None => HeaderVal::Val(str),
+                        HeaderVal::Val(v) => HeaderVal::List(vec!(v, str)),
+                        HeaderVal::List(mut list) => { list.push(str); HeaderVal::List(list) }
                     };
-                    self.header_val = new_val;
                 }
                 DQUOTE => {
-                    match self.state {
-                        HeaderValParserState::QuotedString => { self.state = HeaderValParserState::Token }
-                        HeaderValParserState::CR => { panic!("Parse error!") }
-                        _ => { self.state = HeaderValParserState::QuotedString }
+                    state = match state {
+                        HeaderValParserState::QuotedString => HeaderValParserState::Token,
+                        _ => HeaderValParserState::QuotedString
                     };
                 }
                 _ => {
-                    self.state = HeaderValParserState::Token;
-                    self.buf.push(byte);
+                    state = HeaderValParserState::Token;
+                    token_buf.push(byte);
                 }
             }
         }
 
-        if self.buf.len() > 0 {
-            let val = HeaderVal::Val(String::from_utf8(self.buf.clone()).unwrap_or(String::new()).as_slice().trim().into_string());
-            let str = String::from_utf8(self.buf.clone()).unwrap_or(String::new()).as_slice().trim().into_string();
-            self.buf.clear();
-            let new_val = match &self.header_val {
-                &HeaderVal::None => HeaderVal::Val(str),
-                &HeaderVal::Val(ref v) => HeaderVal::List(vec!(v.clone(), str)),
-                &HeaderVal::List(ref list) => { let mut new_list = list.clone(); new_list.push(str); HeaderVal::List(new_list) }
+        if token_buf.len() > 0 {
+            let str = String::from_utf8(token_buf.clone()).unwrap_or(String::new()).as_slice().trim().into_string();
+            header_val = match header_val {
+                HeaderVal::None => HeaderVal::Val(str),
+                HeaderVal::Val(v) => HeaderVal::List(vec!(v, str)),
+                HeaderVal::List(mut list) => { list.push(str); HeaderVal::List(list) }
             };
-            self.header_val = new_val;
         }
 
-        return self.header_val.clone();
+        return Ok(ParseStatus::Done(header_val, eol + 2));
     }
 }
 
 struct BodyParser {
-    buf: Vec<u8>,
     body_len: usize
 }
 
 impl BodyParser {
     fn new(body_len: usize) -> BodyParser {
-        BodyParser { buf: Vec::new(), body_len: body_len }
+        BodyParser { body_len: body_len }
     }
 }
 
 impl Parser for BodyParser {
-     fn read_req_component(&mut self, stream: &mut TcpStream) -> Vec<u8> {
-        // Reset parser state
-        self.buf.clear();
+    type Output = Vec<u8>;
+
+    fn parse(&mut self, buf: &[u8]) -> Result<ParseStatus<Vec<u8>>, HTTPError> {
+        if buf.len() >= self.body_len {
+            return Ok(ParseStatus::Done(buf[0..self.body_len].to_vec(), self.body_len));
+        }
+        return Ok(ParseStatus::NeedMore);
+    }
+}
+
+// Decodes a `Transfer-Encoding: chunked` body per RFC 7230 section 4.1:
+// a chunk-size line (hex digits, optional `;ext` dropped, CRLF-terminated),
+// that many body bytes plus a trailing CRLF, repeated until a zero-sized
+// chunk, followed by optional trailer headers and the terminating CRLF.
+// Reads byte-by-byte off the shared `Reader` (rather than the raw socket)
+// so it never skips bytes the `Reader` already buffered.
+struct ChunkedParser {
+    line_buf: Vec<u8>,
+    body_buf: Vec<u8>,
+    max_chunk_size_line_len: usize,
+    max_body_len: usize,
+    max_trailer_count: usize
+}
+
+impl ChunkedParser {
+    fn new() -> ChunkedParser {
+        ChunkedParser {
+            line_buf: Vec::new(),
+            body_buf: Vec::new(),
+            max_chunk_size_line_len: 4096us,
+            max_body_len: 10 * 1024 * 1024us,
+            max_trailer_count: 100us
+        }
+    }
+
+    fn read_line(&mut self, reader: &mut Reader) -> Result<Vec<u8>, HTTPError> {
+        self.line_buf.clear();
 
         loop {
-            let byte = stream.read_byte().unwrap();
-            self.buf.push(byte);
-            if self.buf.len() >= self.body_len { break; }
+            let byte = try!(reader.next_byte());
+            if self.line_buf.len() >= self.max_chunk_size_line_len {
+                return Err(HTTPError::BodyParsingError);
+            }
+            match byte {
+                CR => {
+                    match try!(reader.next_byte()) {
+                        LF => break,
+                        _ => return Err(HTTPError::BodyParsingError)
+                    }
+                }
+                _ => { self.line_buf.push(byte); }
+            }
         }
 
-        return self.buf.clone();
+        return Ok(self.line_buf.clone());
     }
+
+    fn read_chunk_size(&mut self, reader: &mut Reader) -> Result<usize, HTTPError> {
+        let line = try!(self.read_line(reader));
+        let line = match String::from_utf8(line) {
+            Ok(s) => s,
+            Err(_) => return Err(HTTPError::BodyParsingError)
+        };
+
+        // Drop any chunk extensions (";ext=value") trailing the hex size:
+        // only the text before the first `;` is parsed as the size.
+        let hex_part = match line.as_slice().splitn(2, ';').next() {
+            Some(s) => s.trim(),
+            None => ""
+        };
+        if hex_part.len() == 0 {
+            return Err(HTTPError::BodyParsingError);
+        }
+
+        return match from_str_radix::<usize>(hex_part, 16) {
+            Some(n) => Ok(n),
+            None => Err(HTTPError::BodyParsingError)
+        };
+    }
+
+    fn read_chunk_data(&mut self, reader: &mut Reader, len: usize) -> Result<(), HTTPError> {
+        // A chunk-size line can encode up to `usize::MAX`, so check via
+        // `checked_add` rather than adding directly and maybe overflowing
+        // past the very cap this is meant to enforce.
+        match self.body_buf.len().checked_add(len) {
+            Some(total) if total <= self.max_body_len => {}
+            _ => return Err(HTTPError::BodyParsingError)
+        }
+
+        for _ in 0..len {
+            let byte = try!(reader.next_byte());
+            self.body_buf.push(byte);
+        }
+
+        match try!(reader.next_byte()) {
+            CR => {}
+            _ => return Err(HTTPError::BodyParsingError)
+        }
+        match try!(reader.next_byte()) {
+            LF => {}
+            _ => return Err(HTTPError::BodyParsingError)
+        }
+
+        return Ok(());
+    }
+
+    fn read_trailers(&mut self, reader: &mut Reader) -> Result<(), HTTPError> {
+        let mut count = 0us;
+
+        loop {
+            let line = try!(self.read_line(reader));
+            if line.len() == 0 { break; }
+
+            count += 1;
+            if count > self.max_trailer_count {
+                return Err(HTTPError::BodyParsingError);
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn read_body(&mut self, reader: &mut Reader) -> Result<Vec<u8>, HTTPError> {
+        self.body_buf.clear();
+
+        loop {
+            let chunk_len = try!(self.read_chunk_size(reader));
+            if chunk_len == 0 {
+                try!(self.read_trailers(reader));
+                break;
+            }
+
+            try!(self.read_chunk_data(reader, chunk_len));
+        }
+
+        return Ok(self.body_buf.clone());
+    }
+}
+
+pub fn header_val_contains(header_val: &HeaderVal, needle: &str) -> bool {
+    match header_val {
+        &HeaderVal::Val(ref v) => v.as_slice().eq_ignore_ascii_case(needle),
+        &HeaderVal::List(ref list) => list.iter().any(|v| v.as_slice().eq_ignore_ascii_case(needle)),
+        &HeaderVal::None => false
+    }
+}
+
+fn read_chunked_body(reader: &mut Reader) -> Result<String, HTTPError> {
+    let mut parser = ChunkedParser::new();
+    let bytes = try!(parser.read_body(reader));
+    return match String::from_utf8(bytes) {
+        Ok(s) => Ok(s),
+        Err(_) => Err(HTTPError::BodyParsingError)
+    };
 }
 
-fn read_request_type(stream: &mut TcpStream) -> Option<RequestType> {
+fn read_request_type(reader: &mut Reader) -> Result<Option<RequestType>, HTTPError> {
     let mut parser = SPParser::new();
-    let component = parser.read_req_component(stream);
-    return match component.as_slice() {
+    let component = try!(reader.parse(&mut parser));
+    return Ok(match component.as_slice() {
         b"GET" => Some(RequestType::GET),
         b"HEAD" => Some(RequestType::HEAD),
         b"POST" => Some(RequestType::POST),
@@ -262,41 +523,44 @@ fn read_request_type(stream: &mut TcpStream) -> Option<RequestType> {
         b"CONNECT" => Some(RequestType::CONNECT),
         b"PATCH" => Some(RequestType::PATCH),
         _ => None
-    };
+    });
 }
 
-fn read_reason(stream: &mut TcpStream) -> Option<String> {
+fn read_reason(reader: &mut Reader) -> Result<Option<String>, HTTPError> {
     let mut parser = EOLParser::new();
-    match String::from_utf8(parser.read_req_component(stream)) {
+    let component = try!(reader.parse(&mut parser));
+    return Ok(match String::from_utf8(component) {
         Ok(s) => Some(s),
-        Err(e) => None
-    }
+        Err(_) => None
+    });
 }
 
-fn read_resource(stream: &mut TcpStream) -> Option<String> {
+fn read_resource(reader: &mut Reader) -> Result<Option<String>, HTTPError> {
     let mut parser = SPParser::new();
-    match String::from_utf8(parser.read_req_component(stream)) {
+    let component = try!(reader.parse(&mut parser));
+    return Ok(match String::from_utf8(component) {
         Ok(s) => Some(s),
-        Err(e) => None
-    }
+        Err(_) => None
+    });
 }
 
-fn read_version<T: Parser>(stream: &mut TcpStream, parser: &mut T) -> Option<Version> {
-    let component = parser.read_req_component(stream);
-    return match component.as_slice() {
+fn read_version<T: Parser<Output = Vec<u8>>>(reader: &mut Reader, parser: &mut T) -> Result<Option<Version>, HTTPError> {
+    let component = try!(reader.parse(parser));
+    return Ok(match component.as_slice() {
         b"HTTP/0.9" => Some(Version::Http09),
         b"HTTP/1.0" => Some(Version::Http10),
         b"HTTP/1.1" => Some(Version::Http11),
         b"Http/2.0" => Some(Version::Http20),
         _ => None
-    };
+    });
 }
 
-fn read_status_code(stream: &mut TcpStream) -> Option<isize> {
+fn read_status_code(reader: &mut Reader) -> Result<Option<isize>, HTTPError> {
     let mut parser = SPParser::new();
-    let status_code_str = String::from_utf8(parser.read_req_component(stream)).unwrap_or(String::new());
+    let component = try!(reader.parse(&mut parser));
+    let status_code_str = String::from_utf8(component).unwrap_or(String::new());
     let status_code = status_code_str.parse::<isize>();
-    match status_code {
+    return Ok(match status_code {
         Some(num) => {
             match num.to_string().len() {
                 3 => Some(num),
@@ -304,112 +568,197 @@ fn read_status_code(stream: &mut TcpStream) -> Option<isize> {
             }
         }
         None => None
-    }
+    });
 }
 
-fn read_req_line(stream: &mut TcpStream) -> Result<(RequestType, String, Version), Error> {
-    let maybe_method = read_request_type(stream);
-    let maybe_resource = read_resource(stream);
-    let maybe_version = read_version(stream, &mut EOLParser::new());
+fn read_req_line(reader: &mut Reader) -> Result<(RequestType, String, Version), HTTPError> {
+    let maybe_method = try!(read_request_type(reader));
+    let maybe_resource = try!(read_resource(reader));
+    let maybe_version = try!(read_version(reader, &mut EOLParser::new()));
 
     if maybe_method.is_none() {
-        return Err(Error::MethodParseError);
+        return Err(HTTPError::MethodParseError);
     }
 
     if maybe_resource.is_none() {
-        return Err(Error::ResourceParseError);
+        return Err(HTTPError::ResourceParseError);
     }
 
     if maybe_version.is_none() {
-        return Err(Error::VersionParseError);
+        return Err(HTTPError::VersionParseError);
     }
 
     return Ok((maybe_method.unwrap(), maybe_resource.unwrap(), maybe_version.unwrap()));
 }
 
-fn read_status_line(stream: &mut TcpStream) -> Result<(Version, isize, String), Error> {
-    let maybe_version = read_version(stream, &mut SPParser::new());
-    let maybe_code = read_status_code(stream);
-    let maybe_reason = read_reason(stream);
+fn read_status_line(reader: &mut Reader) -> Result<(Version, isize, String), HTTPError> {
+    let maybe_version = try!(read_version(reader, &mut SPParser::new()));
+    let maybe_code = try!(read_status_code(reader));
+    let maybe_reason = try!(read_reason(reader));
 
     if maybe_version.is_none() {
-        return Err(Error::VersionParseError);
+        return Err(HTTPError::VersionParseError);
     }
 
     if maybe_code.is_none() {
-        return Err(Error::StatusCodeParseError);
+        return Err(HTTPError::StatusCodeParseError);
     }
 
     if maybe_reason.is_none() {
-        return Err(Error::StatusReasonParseError);
+        return Err(HTTPError::StatusReasonParseError);
     }
 
     return Ok((maybe_version.unwrap(), maybe_code.unwrap(), maybe_reason.unwrap()));
 }
 
-fn read_headers(stream: &mut TcpStream) -> Result<HashMap<String, HeaderVal>, Error> {
+// Caps how many headers a single message may carry and how much buffer
+// space its header section may occupy in total, so a peer can't pin down
+// a serving thread's memory by trickling in an unbounded number of (or
+// unboundedly large) header lines.
+const MAX_HEADER_COUNT: usize = 100us;
+const MAX_HEADER_SECTION_LEN: usize = 128 * 1024us;
+
+fn read_headers(reader: &mut Reader) -> Result<HashMap<String, HeaderVal>, HTTPError> {
     let mut key_parser = HeaderKeyParser::new();
+    let mut line_parser = EOLParser::new();
     let mut val_parser = HeaderValParser::new();
 
+    // Summed from the bytes each header line consumed rather than taken as
+    // a difference of `reader.pos`, which a refill moves back towards 0
+    // when it compacts the buffer.
+    let mut section_len = 0us;
     let mut headers = HashMap::new();
+
     loop {
-        let key = String::from_utf8(key_parser.read_req_component(stream)).unwrap_or(String::new());
+        let key_component = try!(reader.parse(&mut key_parser));
+        let key = String::from_utf8(key_component).unwrap_or(String::new());
         if key.len() == 0 { break; }
-        let val_component = val_parser.read_req_component(stream);;
 
+        if headers.len() >= MAX_HEADER_COUNT {
+            return Err(HTTPError::MalformedHeaderLineError);
+        }
+
+        let mut line = try!(reader.parse(&mut line_parser));
+        section_len += key.len() + 1 + line.len() + 2;
+        line.push_all(b"\r\n");
+        let val_component = match try!(val_parser.parse(line.as_slice())) {
+            ParseStatus::Done(val, _) => val,
+            ParseStatus::NeedMore => return Err(HTTPError::MalformedHeaderLineError)
+        };
         headers.insert(key, val_component);
+
+        if section_len > MAX_HEADER_SECTION_LEN {
+            return Err(HTTPError::MalformedHeaderLineError);
+        }
     }
 
     return Ok(headers);
 }
 
-fn read_body(stream: &mut TcpStream, len: usize) -> String {
+fn read_body(reader: &mut Reader, len: usize) -> Result<String, HTTPError> {
     let mut parser = BodyParser::new(len);
-    String::from_utf8(parser.read_req_component(stream)).unwrap_or(String::new())
+    let component = try!(reader.parse(&mut parser));
+    return Ok(String::from_utf8(component).unwrap_or(String::new()));
 }
 
-pub fn read_request(stream: &mut TcpStream) -> Request {
-    let (method, resource, version) = read_req_line(stream).unwrap();
-    let headers = read_headers(stream).unwrap();
+// True when the request declared `Expect: 100-continue`, i.e. the client is
+// holding its body back until it sees a `100 Continue` status line. Callers
+// that honor this must read the head via `read_request_head`, decide
+// whether to send the interim response, and only then call
+// `read_request_body` — reading a body straight away would block on bytes
+// the client has deliberately not sent yet.
+pub fn expects_continue(headers: &HashMap<String, HeaderVal>) -> bool {
+    match headers.get("Expect") {
+        Some(v) => header_val_contains(v, "100-continue"),
+        None => false
+    }
+}
 
-    let body = if method == RequestType::HEAD {
-        None
-    } else {
-        match headers.get("Content-Length") {
-            None => {
-                match headers.get("Transfer-Encoding") {
-                    None => None,
-                    Some(v) => Some(read_body(stream, 4096))
+// True if `method`/`headers` describe a CONNECT tunnel or a protocol
+// upgrade, either of which hands the socket off to something other than
+// framed HTTP/1.x content, leaving no body to read here.
+fn is_tunnel(method: &RequestType, headers: &HashMap<String, HeaderVal>) -> bool {
+    *method == RequestType::CONNECT || match headers.get("Upgrade") {
+        Some(_) => true,
+        None => match headers.get("Connection") {
+            Some(v) => header_val_contains(v, "upgrade"),
+            None => false
+        }
+    }
+}
+
+// Reads the request line and headers and returns a `Request` with `body`
+// left as `None`. Split out from `read_request` so callers that need to act
+// on `Expect: 100-continue` (see `expects_continue`) can decide whether to
+// read the body at all before calling `read_request_body`.
+pub fn read_request_head(reader: &mut Reader) -> Result<ParsedItem, HTTPError> {
+    if try!(reader.peek(HTTP2_PREFACE_PREFIX.len())).as_slice() == HTTP2_PREFACE_PREFIX {
+        return Ok(ParsedItem::Http2Preface);
+    }
+
+    let (method, resource, version) = try!(read_req_line(reader));
+    let headers = try!(read_headers(reader));
+
+    return Ok(ParsedItem::Request(Request {
+        method: method,
+        version: version,
+        resource: resource,
+        headers: headers,
+        body: None
+    }));
+}
+
+// Reads the body framed by `headers` (Content-Length or chunked
+// Transfer-Encoding), or returns `None` if `method`/`headers` call for no
+// body at all (HEAD, a tunnel, or the absence of either header).
+pub fn read_request_body(reader: &mut Reader, method: &RequestType, headers: &HashMap<String, HeaderVal>) -> Result<Option<String>, HTTPError> {
+    if *method == RequestType::HEAD || is_tunnel(method, headers) {
+        return Ok(None);
+    }
+
+    return match headers.get("Content-Length") {
+        None => {
+            match headers.get("Transfer-Encoding") {
+                None => Ok(None),
+                Some(te) => {
+                    if header_val_contains(te, "chunked") {
+                        Ok(Some(try!(read_chunked_body(reader))))
+                    } else {
+                        Ok(Some(try!(read_body(reader, 4096))))
+                    }
                 }
             }
+        }
 
-            Some(len_field) => {
-                match len_field {
-                    &HeaderVal::Val(ref len_str) => {
-                        let len = len_str.to_string().as_slice().parse::<usize>();
-                        match len {
-                            None => None,
-                            Some(len) => Some(read_body(stream, len))
-                        }
+        Some(len_field) => {
+            match len_field {
+                &HeaderVal::Val(ref len_str) => {
+                    let len = len_str.to_string().as_slice().parse::<usize>();
+                    match len {
+                        None => Ok(None),
+                        Some(len) => Ok(Some(try!(read_body(reader, len))))
                     }
-                    _ => None
                 }
+                _ => Ok(None)
             }
         }
     };
+}
 
-    return Request {
-        method: method,
-        version: version,
-        resource: resource,
-        headers: headers,
-        body: body
+pub fn read_request(reader: &mut Reader) -> Result<ParsedItem, HTTPError> {
+    let mut request = match try!(read_request_head(reader)) {
+        ParsedItem::Request(r) => r,
+        preface @ ParsedItem::Http2Preface => return Ok(preface)
     };
+
+    request.body = try!(read_request_body(reader, &request.method, &request.headers));
+
+    return Ok(ParsedItem::Request(request));
 }
 
-pub fn read_response(stream: &mut TcpStream) -> Response {
-    let (version, status_code, reason) = read_status_line(stream).unwrap();
-    let headers = read_headers(stream).unwrap();
+pub fn read_response(reader: &mut Reader) -> Result<Response, HTTPError> {
+    let (version, status_code, reason) = try!(read_status_line(reader));
+    let headers = try!(read_headers(reader));
 
     let body = match status_code {
         204 => None,
@@ -422,7 +771,13 @@ pub fn read_response(stream: &mut TcpStream) -> Response {
                     None => {
                         match headers.get("Transfer-Encoding") {
                             None => None,
-                            Some(v) => Some(read_body(stream, 4096))
+                            Some(te) => {
+                                if header_val_contains(te, "chunked") {
+                                    Some(try!(read_chunked_body(reader)))
+                                } else {
+                                    Some(try!(read_body(reader, 4096)))
+                                }
+                            }
                         }
                     }
 
@@ -432,7 +787,7 @@ pub fn read_response(stream: &mut TcpStream) -> Response {
                                 let len = len_str.to_string().as_slice().parse::<usize>();
                                 match len {
                                     None => None,
-                                    Some(len) => Some(read_body(stream, len))
+                                    Some(len) => Some(try!(read_body(reader, len)))
                                 }
                             }
                             _ => None
@@ -443,11 +798,156 @@ pub fn read_response(stream: &mut TcpStream) -> Response {
         }
     };
 
-    return Response {
+    return Ok(Response {
         version: version,
         status_code: status_code,
         reason: reason,
         headers: headers,
         body: body
-    };
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{TcpListener, TcpStream};
+    use std::io::{Acceptor, Listener};
+    use std::thread::Thread;
+
+    use super::ParsedItem;
+    use super::{read_request, Reader};
+
+    #[test]
+    fn decodes_a_chunked_request_body() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8484").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut client = TcpStream::connect("127.0.0.1:8484").unwrap();
+            client.write(b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nHello\r\n6\r\n World\r\n0\r\n\r\n").unwrap();
+        });
+
+        let mut stream = acceptor.accept().unwrap();
+        let mut reader = Reader::new(&mut stream);
+        let request = match read_request(&mut reader).unwrap() {
+            ParsedItem::Request(r) => r,
+            ParsedItem::Http2Preface => panic!("expected a request, not an h2 preface")
+        };
+
+        assert_eq!(request.body, Some("Hello World".to_string()));
+    }
+
+    #[test]
+    fn ignores_a_chunk_extension_on_the_size_line() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8489").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut client = TcpStream::connect("127.0.0.1:8489").unwrap();
+            client.write(b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;foo=bar\r\nHello\r\n0\r\n\r\n").unwrap();
+        });
+
+        let mut stream = acceptor.accept().unwrap();
+        let mut reader = Reader::new(&mut stream);
+        let request = match read_request(&mut reader).unwrap() {
+            ParsedItem::Request(r) => r,
+            ParsedItem::Http2Preface => panic!("expected a request, not an h2 preface")
+        };
+
+        assert_eq!(request.body, Some("Hello".to_string()));
+    }
+
+    #[test]
+    fn rejects_a_chunk_size_that_would_overflow_the_body_cap() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8485").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut client = TcpStream::connect("127.0.0.1:8485").unwrap();
+            client.write(b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\n").unwrap();
+        });
+
+        let mut stream = acceptor.accept().unwrap();
+        let mut reader = Reader::new(&mut stream);
+        assert!(read_request(&mut reader).is_err());
+    }
+
+    // The peer trickles the request across several short writes with a
+    // pause between each, so the first `Reader::fill()` call only ever
+    // buffers a few bytes at a time. Every component parser (`SPParser`,
+    // `EOLParser`, the header parsers) must report `NeedMore` and resume
+    // correctly on the next `fill()` rather than assuming a whole line or
+    // token arrives in one `read()`.
+    #[test]
+    fn resumes_parsing_after_a_forced_buffer_refill() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8490").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut client = TcpStream::connect("127.0.0.1:8490").unwrap();
+            for piece in ["GE", "T /sl", "ow HTTP/1.1\r\n", "Host: e", "xample.com\r\n", "\r\n"].iter() {
+                client.write(piece.as_bytes()).unwrap();
+                std::io::timer::sleep(std::time::Duration::milliseconds(20));
+            }
+        });
+
+        let mut stream = acceptor.accept().unwrap();
+        let mut reader = Reader::new(&mut stream);
+        let request = match read_request(&mut reader).unwrap() {
+            ParsedItem::Request(r) => r,
+            ParsedItem::Http2Preface => panic!("expected a request, not an h2 preface")
+        };
+
+        assert_eq!(request.resource, "/slow".to_string());
+    }
+
+    #[test]
+    fn detects_the_http2_client_preface() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8491").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut client = TcpStream::connect("127.0.0.1:8491").unwrap();
+            client.write(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n").unwrap();
+        });
+
+        let mut stream = acceptor.accept().unwrap();
+        let mut reader = Reader::new(&mut stream);
+        match read_request(&mut reader).unwrap() {
+            ParsedItem::Http2Preface => {}
+            ParsedItem::Request(_) => panic!("expected an h2 preface, not a request")
+        }
+    }
+
+    #[test]
+    fn a_connect_request_has_no_body_parsed() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8492").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut client = TcpStream::connect("127.0.0.1:8492").unwrap();
+            client.write(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\nthis should not be read as a body").unwrap();
+        });
+
+        let mut stream = acceptor.accept().unwrap();
+        let mut reader = Reader::new(&mut stream);
+        let request = match read_request(&mut reader).unwrap() {
+            ParsedItem::Request(r) => r,
+            ParsedItem::Http2Preface => panic!("expected a request, not an h2 preface")
+        };
+
+        assert_eq!(request.body, None);
+    }
+
+    #[test]
+    fn an_upgrade_request_has_no_body_parsed() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8493").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut client = TcpStream::connect("127.0.0.1:8493").unwrap();
+            client.write(b"GET /ws HTTP/1.1\r\nHost: example.com\r\nUpgrade: websocket\r\nConnection: upgrade\r\n\r\nthis should not be read as a body").unwrap();
+        });
+
+        let mut stream = acceptor.accept().unwrap();
+        let mut reader = Reader::new(&mut stream);
+        let request = match read_request(&mut reader).unwrap() {
+            ParsedItem::Request(r) => r,
+            ParsedItem::Http2Preface => panic!("expected a request, not an h2 preface")
+        };
+
+        assert_eq!(request.body, None);
+    }
 }
@@ -0,0 +1,172 @@
+use std::io::TcpStream;
+
+use http::{ParsedItem, Request};
+use http::parser;
+use http::parser::Reader;
+
+// Bounds how many pipelined requests a single connection will be walked
+// through before it is forced closed, so a client that keeps firing
+// requests without ever closing (or backing off) can't pin a serving
+// thread and its read buffer open indefinitely.
+const MAX_PIPELINED_REQUESTS: usize = 16us;
+
+// Reads successive requests off `stream`, handing each to `handler`
+// alongside the `Reader` it should use to write the response, and decides
+// after every request whether the connection stays open per the
+// `Connection`/HTTP-version rules in `Request::keep_alive`. Supports basic
+// pipelining: if the client already sent the next request's bytes before
+// this one's response went out, they are sitting in the `Reader`'s buffer
+// and get parsed straight out of it rather than triggering another read.
+//
+// Always accepts `Expect: 100-continue` requests; use
+// `serve_connection_with_expect` to decide that per request instead.
+pub fn serve_connection<F>(stream: &mut TcpStream, handler: F) where F: FnMut(&Request, &mut Reader) {
+    serve_connection_with_expect(stream, |_req| true, handler)
+}
+
+// Like `serve_connection`, but calls `accept_continue` with the
+// not-yet-bodied request whenever it declares `Expect: 100-continue`. If it
+// returns true, a `100 Continue` status line is sent and the body is read
+// normally; if false, a `417 Expectation Failed` is sent and the body is
+// left unread (the client is withholding it until it either sees the 100 it
+// will now never get, or gives up and closes the connection itself).
+pub fn serve_connection_with_expect<F, G>(stream: &mut TcpStream, mut accept_continue: G, mut handler: F)
+    where F: FnMut(&Request, &mut Reader), G: FnMut(&Request) -> bool
+{
+    let mut reader = Reader::new(stream);
+    let mut served = 0us;
+
+    loop {
+        // A malformed request or a broken socket ends this connection
+        // rather than the serving task: whatever is wrong with this one
+        // client shouldn't take the thread (and every other connection it
+        // might go on to serve) down with it.
+        let mut request = match parser::read_request_head(&mut reader) {
+            Ok(ParsedItem::Request(request)) => request,
+            // No h2 implementation yet; stop driving this connection as
+            // HTTP/1.x so the caller can hand the socket off separately.
+            Ok(ParsedItem::Http2Preface) => break,
+            Err(_) => break
+        };
+
+        if parser::expects_continue(&request.headers) {
+            if accept_continue(&request) {
+                if reader.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").is_err() { break; }
+                request.body = match parser::read_request_body(&mut reader, &request.method, &request.headers) {
+                    Ok(body) => body,
+                    Err(_) => break
+                };
+            } else {
+                // The client is withholding the body until it sees a 100
+                // that will now never come, so there is no way to know
+                // where its next request line (if any) begins. Send the
+                // rejection and close the connection without ever handing
+                // this request to `handler` — otherwise `handler` would
+                // write its own response right behind the 417, producing
+                // two status lines for one request.
+                reader.write_all(b"HTTP/1.1 417 Expectation Failed\r\n\r\n").ok();
+                break;
+            }
+        } else {
+            request.body = match parser::read_request_body(&mut reader, &request.method, &request.headers) {
+                Ok(body) => body,
+                Err(_) => break
+            };
+        }
+
+        let keep_alive = request.keep_alive();
+
+        handler(&request, &mut reader);
+
+        served += 1;
+        if !keep_alive || served >= MAX_PIPELINED_REQUESTS { break; }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{TcpListener, TcpStream};
+    use std::io::{Acceptor, Listener};
+    use std::sync::{Arc, Mutex};
+    use std::thread::Thread;
+
+    use super::serve_connection_with_expect;
+
+    #[test]
+    fn rejected_100_continue_closes_the_connection_without_calling_handler() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8483").listen().unwrap();
+
+        let handler_called = Arc::new(Mutex::new(false));
+        let handler_called_in_thread = handler_called.clone();
+
+        Thread::spawn(move|| {
+            let mut stream = acceptor.accept().unwrap();
+            serve_connection_with_expect(&mut stream, |_req| false, |_req, _reader| {
+                *handler_called_in_thread.lock().unwrap() = true;
+            });
+        });
+
+        let mut client = TcpStream::connect("127.0.0.1:8483").unwrap();
+        client.write(b"POST /upload HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n").unwrap();
+
+        // The server must close right after the 417 rather than sending a
+        // second, handler-written response behind it.
+        let response = client.read_to_end().unwrap();
+        assert_eq!(response.as_slice(), b"HTTP/1.1 417 Expectation Failed\r\n\r\n");
+        assert_eq!(*handler_called.lock().unwrap(), false);
+    }
+
+    #[test]
+    fn pipelined_requests_are_served_until_connection_close() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8488").listen().unwrap();
+
+        let served = Arc::new(Mutex::new(0us));
+        let served_in_thread = served.clone();
+
+        Thread::spawn(move|| {
+            let mut stream = acceptor.accept().unwrap();
+            serve_connection_with_expect(&mut stream, |_req| true, |_req, reader| {
+                *served_in_thread.lock().unwrap() += 1;
+                reader.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            });
+        });
+
+        let mut client = TcpStream::connect("127.0.0.1:8488").unwrap();
+        // Both requests are sent before either response comes back, so the
+        // second one is sitting in the `Reader`'s buffer (pipelined) rather
+        // than triggering its own socket read.
+        client.write(b"GET /first HTTP/1.1\r\n\r\nGET /second HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+
+        let response = client.read_to_end().unwrap();
+        let text = String::from_utf8(response).unwrap();
+
+        assert_eq!(text.matches("200 OK").count(), 2);
+        assert_eq!(*served.lock().unwrap(), 2us);
+    }
+
+    #[test]
+    fn accepted_100_continue_reads_the_body_and_calls_handler() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8494").listen().unwrap();
+
+        let handler_body = Arc::new(Mutex::new(None));
+        let handler_body_in_thread = handler_body.clone();
+
+        Thread::spawn(move|| {
+            let mut stream = acceptor.accept().unwrap();
+            serve_connection_with_expect(&mut stream, |_req| true, |req, reader| {
+                *handler_body_in_thread.lock().unwrap() = req.body.clone();
+                reader.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            });
+        });
+
+        let mut client = TcpStream::connect("127.0.0.1:8494").unwrap();
+        client.write(b"POST /upload HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello").unwrap();
+
+        // The 100 Continue status line must be written before the body is
+        // read and before handler's own response, so both show up in order
+        // on the wire ahead of anything else.
+        let response = client.read_to_end().unwrap();
+        assert_eq!(response.as_slice(), b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(*handler_body.lock().unwrap(), Some("hello".to_string()));
+    }
+}
@@ -0,0 +1,236 @@
+use std::io::TcpStream;
+use std::collections::HashMap;
+
+use http::{HeaderVal, Request, Response, Version};
+use http::parser::header_val_contains;
+
+// Owns the socket a `Request`/`Response` is serialized onto. The mirror
+// image of `parser::Reader`: where `Reader` buffers bytes coming off the
+// stream, `Writer` just forwards bytes going out.
+pub struct Writer<'a> {
+    stream: &'a mut TcpStream
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(stream: &'a mut TcpStream) -> Writer<'a> {
+        Writer { stream: stream }
+    }
+
+    fn write_bytes(&mut self, buf: &[u8]) {
+        self.stream.write(buf).unwrap();
+    }
+}
+
+fn version_str(version: &Version) -> &'static str {
+    match *version {
+        Version::Http09 => "HTTP/0.9",
+        Version::Http10 => "HTTP/1.0",
+        Version::Http11 => "HTTP/1.1",
+        Version::Http20 => "HTTP/2.0"
+    }
+}
+
+fn header_val_str(val: &HeaderVal) -> String {
+    match val {
+        &HeaderVal::Val(ref v) => v.clone(),
+        &HeaderVal::List(ref list) => list.connect(", "),
+        &HeaderVal::None => String::new()
+    }
+}
+
+// Frames `body` as chunked transfer-coding: a single data chunk (if
+// non-empty) followed by the zero-length terminating chunk. Good enough
+// for a body we already have in hand in full; a streaming caller would
+// call a lower-level chunk writer per piece of data instead.
+fn write_chunked_body(writer: &mut Writer, body: &[u8]) {
+    if body.len() > 0 {
+        writer.write_bytes(format!("{:x}\r\n", body.len()).as_bytes());
+        writer.write_bytes(body);
+        writer.write_bytes(b"\r\n");
+    }
+    writer.write_bytes(b"0\r\n\r\n");
+}
+
+// Renders `headers` followed by the blank line that ends the header
+// section, then `body`. When `chunked` is false, `Content-Length` is set
+// from the actual length of `body` rather than trusted from `headers`, so
+// the two can never disagree.
+fn write_headers_and_body(writer: &mut Writer, headers: &HashMap<String, HeaderVal>, body: &[u8], chunked: bool) {
+    let mut out_headers = headers.clone();
+    if chunked {
+        // A caller's `headers` map may still carry a stale `Content-Length`
+        // from before `Transfer-Encoding: chunked` was decided; sending
+        // both on the same message is the CL/TE ambiguity this function
+        // exists to rule out.
+        out_headers.remove("Content-Length");
+    } else {
+        out_headers.insert("Content-Length".to_string(), HeaderVal::Val(body.len().to_string()));
+    }
+
+    for (key, val) in out_headers.iter() {
+        writer.write_bytes(key.as_bytes());
+        writer.write_bytes(b": ");
+        writer.write_bytes(header_val_str(val).as_bytes());
+        writer.write_bytes(b"\r\n");
+    }
+    writer.write_bytes(b"\r\n");
+
+    if chunked {
+        write_chunked_body(writer, body);
+    } else {
+        writer.write_bytes(body);
+    }
+}
+
+pub fn write_request(request: &Request, writer: &mut Writer) {
+    let start_line = format!("{:?} {} {}\r\n", request.method, request.resource, version_str(&request.version));
+    writer.write_bytes(start_line.as_bytes());
+
+    let chunked = match request.headers.get("Transfer-Encoding") {
+        Some(te) => header_val_contains(te, "chunked"),
+        None => false
+    };
+    let body = request.body.as_ref().map(|b| b.as_bytes()).unwrap_or(b"");
+
+    write_headers_and_body(writer, &request.headers, body, chunked);
+}
+
+pub fn write_response(response: &Response, writer: &mut Writer) {
+    let start_line = format!("{} {} {}\r\n", version_str(&response.version), response.status_code, response.reason);
+    writer.write_bytes(start_line.as_bytes());
+
+    let chunked = match response.headers.get("Transfer-Encoding") {
+        Some(te) => header_val_contains(te, "chunked"),
+        None => false
+    };
+    let body = response.body.as_ref().map(|b| b.as_bytes()).unwrap_or(b"");
+
+    write_headers_and_body(writer, &response.headers, body, chunked);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{TcpListener, TcpStream};
+    use std::io::{Acceptor, Listener};
+    use std::thread::Thread;
+    use std::collections::HashMap;
+
+    use http::{HeaderVal, Request, RequestType, Response, Version};
+
+    use super::{Writer, write_request, write_response};
+
+    #[test]
+    fn chunked_response_never_carries_a_stale_content_length() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8486").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut stream = acceptor.accept().unwrap();
+
+            let mut headers = HashMap::new();
+            headers.insert("Content-Length".to_string(), HeaderVal::Val("999".to_string()));
+            headers.insert("Transfer-Encoding".to_string(), HeaderVal::Val("chunked".to_string()));
+
+            let response = Response {
+                version: Version::Http11,
+                status_code: 200,
+                reason: "OK".to_string(),
+                headers: headers,
+                body: Some("hi".to_string())
+            };
+
+            let mut writer = Writer::new(&mut stream);
+            write_response(&response, &mut writer);
+        });
+
+        let mut client = TcpStream::connect("127.0.0.1:8486").unwrap();
+        let raw = client.read_to_end().unwrap();
+        let text = String::from_utf8(raw).unwrap();
+
+        assert!(!text.contains("Content-Length"));
+        assert!(text.contains("Transfer-Encoding: chunked"));
+        assert!(text.ends_with("2\r\nhi\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn write_request_serializes_the_start_line_headers_and_body() {
+        let mut acceptor = TcpListener::bind("127.0.0.1:8495").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut stream = acceptor.accept().unwrap();
+
+            let mut headers = HashMap::new();
+            headers.insert("Host".to_string(), HeaderVal::Val("example.com".to_string()));
+
+            let request = Request {
+                method: RequestType::POST,
+                version: Version::Http11,
+                resource: "/upload".to_string(),
+                headers: headers,
+                body: Some("hi".to_string())
+            };
+
+            let mut writer = Writer::new(&mut stream);
+            write_request(&request, &mut writer);
+        });
+
+        let mut client = TcpStream::connect("127.0.0.1:8495").unwrap();
+        let raw = client.read_to_end().unwrap();
+        let text = String::from_utf8(raw).unwrap();
+
+        assert!(text.starts_with("POST /upload HTTP/1.1\r\n"));
+        assert!(text.contains("Host: example.com\r\n"));
+        assert!(text.contains("Content-Length: 2\r\n"));
+        assert!(text.ends_with("\r\n\r\nhi"));
+    }
+
+    #[test]
+    fn non_chunked_bodies_get_an_auto_content_length_for_requests_and_responses() {
+        let mut request_acceptor = TcpListener::bind("127.0.0.1:8496").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut stream = request_acceptor.accept().unwrap();
+
+            let request = Request {
+                method: RequestType::GET,
+                version: Version::Http11,
+                resource: "/".to_string(),
+                headers: HashMap::new(),
+                body: None
+            };
+
+            let mut writer = Writer::new(&mut stream);
+            write_request(&request, &mut writer);
+        });
+
+        let mut request_client = TcpStream::connect("127.0.0.1:8496").unwrap();
+        let request_raw = request_client.read_to_end().unwrap();
+        let request_text = String::from_utf8(request_raw).unwrap();
+
+        assert!(request_text.contains("Content-Length: 0\r\n"));
+        assert!(!request_text.contains("Transfer-Encoding"));
+
+        let mut response_acceptor = TcpListener::bind("127.0.0.1:8497").listen().unwrap();
+
+        Thread::spawn(move|| {
+            let mut stream = response_acceptor.accept().unwrap();
+
+            let response = Response {
+                version: Version::Http11,
+                status_code: 200,
+                reason: "OK".to_string(),
+                headers: HashMap::new(),
+                body: Some("hi".to_string())
+            };
+
+            let mut writer = Writer::new(&mut stream);
+            write_response(&response, &mut writer);
+        });
+
+        let mut response_client = TcpStream::connect("127.0.0.1:8497").unwrap();
+        let response_raw = response_client.read_to_end().unwrap();
+        let response_text = String::from_utf8(response_raw).unwrap();
+
+        assert!(response_text.contains("Content-Length: 2\r\n"));
+        assert!(response_text.ends_with("hi"));
+    }
+}
@@ -6,6 +6,9 @@ use std::str::from_utf8;
 use std::collections::HashMap;
 use std::thread::Thread;
 
+use epic::http::{HeaderVal, Response, Version};
+use epic::http::writer::{Writer, write_response};
+
 fn main() {
     let mut acceptor = TcpListener::bind("127.0.0.1:8482").listen().unwrap();
 
@@ -13,12 +16,23 @@ fn main() {
         for socket in acceptor.incoming() {
             match socket {
                 Ok(mut stream) => {
-                    let req = epic::http::parser::read_request(&mut stream);
-                    println!("Req: {:?}", req);
+                    epic::http::server::serve_connection(&mut stream, |req, reader| {
+                        println!("Req: {:?}", req);
+
+                        let mut headers = HashMap::new();
+                        headers.insert("Content-Type".to_string(), HeaderVal::Val("text/plain".to_string()));
+
+                        let response = Response {
+                            version: Version::Http11,
+                            status_code: 200,
+                            reason: "VERY OK".to_string(),
+                            headers: headers,
+                            body: Some("Hello world!".to_string())
+                        };
 
-                    // Write something back
-                    stream.write(b"HTTP/1.1 200 VERY OK\r\nContent-Type: text/plain\r\nContent-Length:12\r\n\r\nHello");
-                    stream.write(b" world!");
+                        let mut writer = Writer::new(reader.stream());
+                        write_response(&response, &mut writer);
+                    });
                 }
                 // Err(ref e) if e.kind == EndOfFile => break, // closed
                 Err(e) => panic!("unexpected error: {}", e),
@@ -30,5 +44,6 @@ fn main() {
     let mut stream = TcpStream::connect("127.0.0.1:8482").unwrap();
     stream.write(b"GET /index.html HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length:12\r\nTransfer-Encoding: gzip, chunked\r\n\r\nHello").unwrap();
     stream.write(b" world!").unwrap();
-    println!("Client got: {:?}", epic::http::parser::read_response(&mut stream));
+    let mut reader = epic::http::parser::Reader::new(&mut stream);
+    println!("Client got: {:?}", epic::http::parser::read_response(&mut reader).unwrap());
 }